@@ -2,13 +2,17 @@ use std::{
     env,
     fs::{self, File},
     io::Write,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use ahash::AHashMap;
 use anyhow::{bail, Context, Result};
-use clap::{Parser, Subcommand};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use clap::{Parser, Subcommand, ValueEnum};
 use dotenvy::dotenv;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 #[derive(Parser)]
@@ -21,6 +25,26 @@ struct Cli {
     #[arg(short, long)]
     quiet: bool,
 
+    /// Maximum number of retries for a request that fails with a transport error or a 5xx/429 response
+    #[arg(long, default_value_t = 4)]
+    max_retries: u32,
+
+    /// Base delay in milliseconds for the exponential backoff between retries
+    #[arg(long, default_value_t = 500)]
+    retry_base_delay_ms: u64,
+
+    /// Print what Upload/Remove/Sync would do without making any mutating request
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Base URL of the LMS API. Falls back to the LMS_BASE_URL environment variable (.env supported).
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Id of the training module whose materials are managed. Falls back to the LMS_MODULE_ID environment variable (.env supported).
+    #[arg(long)]
+    module_id: Option<u64>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -39,13 +63,26 @@ enum Commands {
     /// Log in to GoITeens admin panel using environment variables LMS_USERNAME and LMS_PASSWORD (.env supported)
     LoginEnv,
 
-    /// Upload records into the LMS for a group from input.txt file
+    /// Upload records into the LMS for a group from an input file (input.txt by default)
     ///
-    /// input.txt has tech skills and soft skills lessons separated by double newline.
-    /// Each lesson is is tab-separated line with the lesson's name and a link to its record.
+    /// The default tsv format has tech skills and soft skills lessons separated by a double
+    /// newline. Each lesson is a tab-separated line with the lesson's name and a link to its
+    /// record. The json format is an array of `{ "type": "tech"|"soft", "name", "links",
+    /// "group_id" }` objects, where a per-entry `group_id` overrides the one given here and
+    /// lets a single file target several groups. The csv format has `type,name,links` columns,
+    /// with multiple links in a cell separated by `;`.
     Upload {
         /// Id of the affected group. Can be obtained by copying it from the group's URL (it's the first number).
-        group_id: u64,
+        /// Optional if every entry in the input file specifies its own group_id.
+        group_id: Option<u64>,
+
+        /// Path to the input file
+        #[arg(long, default_value = "./input.txt")]
+        input: PathBuf,
+
+        /// Input file format. Defaults to detecting it from the input file's extension, falling back to tsv.
+        #[arg(long, value_enum)]
+        format: Option<InputFormat>,
     },
 
     /// Remove all lesson records for a group
@@ -53,6 +90,71 @@ enum Commands {
         /// Id of the affected group. Can be obtained by copying it from the group's URL (it's the first number).
         group_id: u64,
     },
+
+    /// Sync records for a group with an input file, only creating/deleting what changed
+    ///
+    /// Unlike Remove followed by Upload, this diffs the lessons already present in the LMS
+    /// against the lessons described by the input file: matching name+link pairs are left alone,
+    /// missing ones are created and stale ones are deleted. This keeps re-running the command
+    /// idempotent, where re-running Upload would create every lesson again. Lessons sharing a
+    /// name in the input file still get the same "(2)" duplicate-name suffixes as in Upload.
+    /// Accepts the same tsv/json/csv input formats as Upload.
+    Sync {
+        /// Id of the affected group. Can be obtained by copying it from the group's URL (it's the first number).
+        /// Optional if every entry in the input file specifies its own group_id.
+        group_id: Option<u64>,
+
+        /// Path to the input file
+        #[arg(long, default_value = "./input.txt")]
+        input: PathBuf,
+
+        /// Input file format. Defaults to detecting it from the input file's extension, falling back to tsv.
+        #[arg(long, value_enum)]
+        format: Option<InputFormat>,
+    },
+}
+
+/// The recognized input file formats for Upload and Sync.
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    Tsv,
+    Json,
+    Csv,
+}
+
+const DEFAULT_BASE_URL: &str = "https://api.admin.edu.goiteens.com";
+const DEFAULT_MODULE_ID: u64 = 17063573;
+
+/// The LMS API endpoint and training module that all commands operate against.
+#[derive(Clone)]
+struct LmsConfig {
+    base_url: String,
+    module_id: u64,
+}
+
+impl LmsConfig {
+    fn resolve(base_url: Option<String>, module_id: Option<u64>) -> Result<LmsConfig> {
+        let base_url = base_url
+            .or_else(|| env::var("LMS_BASE_URL").ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        let module_id = match module_id {
+            Some(module_id) => module_id,
+            None => match env::var("LMS_MODULE_ID") {
+                Ok(value) => value
+                    .parse()
+                    .context("LMS_MODULE_ID environment variable is not a valid number")?,
+                Err(_) => DEFAULT_MODULE_ID,
+            },
+        };
+
+        Ok(LmsConfig {
+            base_url,
+            module_id,
+        })
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -70,6 +172,12 @@ struct TokenResponse {
     access_token: String,
 }
 
+/// The subset of JWT claims we care about when deciding whether a cached access token is stale.
+#[derive(Deserialize)]
+struct JwtClaims {
+    exp: u64,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct GenericResponse {
@@ -91,9 +199,13 @@ struct LessonListResponse {
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct LessonResponse {
     id: u64,
     name: String,
+    link: String,
+    #[serde(rename = "type")]
+    lesson_type: String,
 }
 
 impl Lesson {
@@ -131,101 +243,354 @@ fn truncate_chars(s: &str, max_chars: usize) -> &str {
     }
 }
 
+/// Classifies a material's link the same way the LMS API expects in its `type` field.
+fn material_type(link: &str) -> &'static str {
+    if link.contains("youtu") {
+        "video"
+    } else {
+        "other"
+    }
+}
+
+/// A lesson paired with the id of the group it should be uploaded to or reconciled against.
+/// Carrying the group id alongside the lesson (rather than as a single argument) is what lets a
+/// single json input file target several groups.
+struct InputLesson {
+    lesson: Lesson,
+    group_id: u64,
+}
+
+/// The `"type"` discriminant used by the json and csv input formats.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JsonLessonKind {
+    Tech,
+    Soft,
+}
+
+impl From<JsonLessonKind> for LessonType {
+    fn from(kind: JsonLessonKind) -> LessonType {
+        match kind {
+            JsonLessonKind::Tech => LessonType::TechSkills,
+            JsonLessonKind::Soft => LessonType::SoftSkills,
+        }
+    }
+}
+
+/// Picks an input format: the explicit `--format`, or the extension of `path`, or tsv.
+fn detect_format(path: &Path, format: Option<InputFormat>) -> InputFormat {
+    format.unwrap_or_else(|| match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => InputFormat::Json,
+        Some("csv") => InputFormat::Csv,
+        _ => InputFormat::Tsv,
+    })
+}
+
+/// Adds the `" (n)"` duplicate-name suffix to lessons sharing a name, same as a same-named
+/// multi-link entry gets its `" (n)"` position suffix.
+fn apply_duplicate_markers(lessons: &mut [InputLesson]) {
+    let mut lesson_counts = AHashMap::default();
+
+    for entry in lessons.iter_mut() {
+        let count = *lesson_counts
+            .entry((entry.group_id, entry.lesson.name.clone()))
+            .and_modify(|count| *count += 1)
+            .or_insert(1u8);
+        if count > 1 {
+            let marker = format!(" ({count})");
+            entry.lesson.name = format!(
+                "{}{marker}",
+                truncate_chars(&entry.lesson.name, 70 - marker.len())
+            );
+        }
+    }
+}
+
+/// Parses an input file into the `Vec<InputLesson>` that Upload and Sync both work from.
+fn build_lessons(
+    path: &Path,
+    format: InputFormat,
+    default_group_id: Option<u64>,
+) -> Result<Vec<InputLesson>> {
+    match format {
+        InputFormat::Tsv => build_lessons_tsv(path, default_group_id),
+        InputFormat::Json => build_lessons_json(path, default_group_id),
+        InputFormat::Csv => build_lessons_csv(path, default_group_id),
+    }
+}
+
+fn build_lessons_tsv(path: &Path, default_group_id: Option<u64>) -> Result<Vec<InputLesson>> {
+    let group_id =
+        default_group_id.context("group_id is required when using the tsv input format")?;
+
+    let lessons = fs::read_to_string(path)
+        .with_context(|| format!("{} not found", path.display()))?
+        .replace("\r\n", "\n")
+        .replace("\n\t", " ");
+
+    let (tech_skills, soft_skills) = lessons.split_once("\n\n").unwrap_or((&lessons, ""));
+
+    let tech_skills = tech_skills
+        .lines()
+        .filter_map(|lesson| match lesson.split_once('\t') {
+            None => None,
+            Some((_, "")) => None,
+            full => full,
+        });
+
+    let soft_skills = soft_skills
+        .lines()
+        .filter_map(|lesson| match lesson.split_once('\t') {
+            None => None,
+            Some((_, "")) => None,
+            full => full,
+        });
+
+    let mut lessons = vec![];
+
+    for ((name, link), lesson_type) in tech_skills
+        .map(|lesson| (lesson, LessonType::TechSkills))
+        .chain(soft_skills.map(|lesson| (lesson, LessonType::SoftSkills)))
+    {
+        if link.contains(' ') {
+            let links: Vec<_> = link.split(' ').filter(|str| !str.is_empty()).collect();
+            for (i, link) in links.into_iter().enumerate() {
+                lessons.push(InputLesson {
+                    lesson: Lesson::new(name, link, Some(i), lesson_type),
+                    group_id,
+                });
+            }
+        } else {
+            lessons.push(InputLesson {
+                lesson: Lesson::new(name, link, None, lesson_type),
+                group_id,
+            });
+        }
+    }
+
+    apply_duplicate_markers(&mut lessons);
+    Ok(lessons)
+}
+
+/// One entry of the json input format: `{ "type": "tech"|"soft", "name", "links", "group_id" }`.
+#[derive(Deserialize)]
+struct JsonLessonEntry {
+    #[serde(rename = "type")]
+    kind: JsonLessonKind,
+    name: String,
+    links: Vec<String>,
+    group_id: Option<u64>,
+}
+
+fn build_lessons_json(path: &Path, default_group_id: Option<u64>) -> Result<Vec<InputLesson>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("{} not found", path.display()))?;
+    let entries: Vec<JsonLessonEntry> =
+        serde_json::from_str(&contents).context("Invalid json input")?;
+
+    let mut lessons = vec![];
+
+    for entry in entries {
+        let group_id = entry.group_id.or(default_group_id).context(
+            "group_id is required: supply it per-entry in the input file or as the group_id argument",
+        )?;
+        let lesson_type = entry.kind.into();
+
+        if entry.links.is_empty() {
+            bail!("Invalid json input: lesson \"{}\" has no links", entry.name);
+        }
+
+        if entry.links.len() > 1 {
+            for (i, link) in entry.links.iter().enumerate() {
+                lessons.push(InputLesson {
+                    lesson: Lesson::new(&entry.name, link, Some(i), lesson_type),
+                    group_id,
+                });
+            }
+        } else {
+            lessons.push(InputLesson {
+                lesson: Lesson::new(&entry.name, &entry.links[0], None, lesson_type),
+                group_id,
+            });
+        }
+    }
+
+    apply_duplicate_markers(&mut lessons);
+    Ok(lessons)
+}
+
+/// One row of the csv input format: `type,name,links,group_id`, multiple links in a cell
+/// separated by `;`.
+#[derive(Deserialize)]
+struct CsvLessonRow {
+    #[serde(rename = "type")]
+    kind: JsonLessonKind,
+    name: String,
+    links: String,
+    group_id: Option<u64>,
+}
+
+fn build_lessons_csv(path: &Path, default_group_id: Option<u64>) -> Result<Vec<InputLesson>> {
+    let mut reader =
+        csv::Reader::from_path(path).with_context(|| format!("{} not found", path.display()))?;
+
+    let mut lessons = vec![];
+
+    for row in reader.deserialize() {
+        let row: CsvLessonRow = row.context("Invalid csv input")?;
+        let group_id = row.group_id.or(default_group_id).context(
+            "group_id is required: supply it per-entry in the input file or as the group_id argument",
+        )?;
+        let lesson_type = row.kind.into();
+
+        let links: Vec<_> = row
+            .links
+            .split(';')
+            .map(str::trim)
+            .filter(|link| !link.is_empty())
+            .collect();
+
+        if links.is_empty() {
+            bail!("Invalid csv input: lesson \"{}\" has no links", row.name);
+        }
+
+        if links.len() > 1 {
+            for (i, link) in links.iter().enumerate() {
+                lessons.push(InputLesson {
+                    lesson: Lesson::new(&row.name, link, Some(i), lesson_type),
+                    group_id,
+                });
+            }
+        } else {
+            lessons.push(InputLesson {
+                lesson: Lesson::new(&row.name, links[0], None, lesson_type),
+                group_id,
+            });
+        }
+    }
+
+    apply_duplicate_markers(&mut lessons);
+    Ok(lessons)
+}
+
+/// Sends a JSON POST request, retrying transport errors and 5xx/429 responses with exponential
+/// backoff. `request` is called again on every attempt since a `ureq::Request` is consumed by
+/// `send_json`. Genuine 4xx errors (other than 429) are returned immediately.
+fn send_json_with_retries(
+    request: impl Fn() -> ureq::Request,
+    body: serde_json::Value,
+    max_retries: u32,
+    base_delay_ms: u64,
+) -> Result<ureq::Response> {
+    let mut attempt = 0;
+    loop {
+        match request().send_json(body.clone()) {
+            Ok(res) => return Ok(res),
+            Err(err) => {
+                let retryable = match &err {
+                    ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+                    ureq::Error::Transport(_) => true,
+                };
+                if retryable && attempt < max_retries {
+                    // Cap the exponent so a large --max-retries can't overflow the backoff delay.
+                    let backoff_factor = 2u64.pow(attempt.min(20));
+                    let delay_ms = base_delay_ms.saturating_mul(backoff_factor);
+                    thread::sleep(Duration::from_millis(delay_ms));
+                    attempt += 1;
+                    continue;
+                }
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+/// Fetches the materials currently present in the LMS for a group.
+fn get_lessons(
+    agent: &ureq::Agent,
+    access_token: &str,
+    group_id: u64,
+    config: &LmsConfig,
+) -> Result<Vec<LessonResponse>> {
+    let res: LessonListResponse = agent
+        .get(&format!(
+            "{}/api/v1/training-module/additional-material/list?moduleId={}&groupId={group_id}",
+            config.base_url, config.module_id
+        ))
+        .set("Authorization", &format!("Bearer {access_token}"))
+        .call()?
+        .into_json()?;
+
+    if !res.success {
+        bail!("GoITeens LMS returned an error: {}", res.error);
+    }
+
+    res.group
+        .context("GoITeens LMS returned an invalid response")
+}
+
 fn main() -> Result<()> {
     dotenv().ok();
 
     let cli = Cli::parse();
 
+    let config = LmsConfig::resolve(cli.base_url.clone(), cli.module_id)?;
+
     let agent = ureq::AgentBuilder::new().build();
 
     match cli.command {
         Commands::Login { username, password } => {
-            log_in(&username, &password, cli.quiet)?;
+            log_in(&username, &password, cli.quiet, &config)?;
         }
         Commands::LoginEnv => {
             let username =
                 env::var("LMS_USERNAME").context("No LMS_USERNAME environment variable found")?;
             let password =
                 env::var("LMS_PASSWORD").context("No LMS_PASSWORD environment variable found")?;
-            log_in(&username, &password, cli.quiet)?;
+            log_in(&username, &password, cli.quiet, &config)?;
         }
-        Commands::Upload { group_id } => {
+        Commands::Upload {
+            group_id,
+            input,
+            format,
+        } => {
             let refresh_token = get_refresh_token()?;
-            let access_token = get_access_token(&refresh_token)?;
-
-            let lessons = fs::read_to_string("./input.txt")
-                .context("input.txt file not found")?
-                .replace("\r\n", "\n")
-                .replace("\n\t", " ");
-
-            let (tech_skills, soft_skills) = lessons.split_once("\n\n").unwrap_or((&lessons, ""));
-
-            let tech_skills =
-                tech_skills
-                    .lines()
-                    .filter_map(|lesson| match lesson.split_once('\t') {
-                        None => None,
-                        Some((_, "")) => None,
-                        full => full,
-                    });
-
-            let soft_skills =
-                soft_skills
-                    .lines()
-                    .filter_map(|lesson| match lesson.split_once('\t') {
-                        None => None,
-                        Some((_, "")) => None,
-                        full => full,
-                    });
-
-            let mut lessons = vec![];
-
-            for ((name, link), lesson_type) in tech_skills
-                .map(|lesson| (lesson, LessonType::TechSkills))
-                .chain(soft_skills.map(|lesson| (lesson, LessonType::SoftSkills)))
-            {
-                if link.contains(' ') {
-                    let links: Vec<_> = link.split(' ').filter(|str| !str.is_empty()).collect();
-                    for (i, link) in links.into_iter().enumerate() {
-                        lessons.push(Lesson::new(name, link, Some(i), lesson_type));
-                    }
-                } else {
-                    lessons.push(Lesson::new(name, link, None, lesson_type));
-                }
-            }
+            let access_token = get_access_token(&refresh_token, &config)?;
+
+            let format = detect_format(&input, format);
+            let lessons = build_lessons(&input, format, group_id)?;
+
+            for InputLesson { lesson, group_id } in lessons {
+                let lesson_type = material_type(&lesson.link);
 
-            let mut lesson_counts = AHashMap::default();
-
-            for lesson in &mut lessons {
-                let count = *lesson_counts
-                    .entry(lesson.name.clone())
-                    .and_modify(|count| *count += 1)
-                    .or_insert(1u8);
-                if count > 1 {
-                    let marker = format!(" ({count})");
-                    lesson.name = format!(
-                        "{}{marker}",
-                        truncate_chars(&lesson.name, 70 - marker.len())
+                if cli.dry_run {
+                    println!(
+                        "Would create {lesson_type} lesson \"{}\" -> {}",
+                        lesson.name, lesson.link
                     );
+                    continue;
                 }
-            }
 
-            for lesson in lessons {
-                let lesson_type = if lesson.link.contains("youtu") {
-                    "video"
-                } else {
-                    "other"
-                };
-                let res: GenericResponse = agent.post("https://api.admin.edu.goiteens.com/api/v1/training-module/additional-material/create")
-                .set("Authorization", &format!("Bearer {access_token}"))
-                .send_json(json!({
-                    "category": "group",
-                    "type": lesson_type,
-                    "moduleId": 17063573,
-                    "groupId": group_id,
-                    "name": lesson.name,
-                    "link": lesson.link
-                }))?
+                let res: GenericResponse = send_json_with_retries(
+                    || {
+                        agent
+                            .post(&format!(
+                                "{}/api/v1/training-module/additional-material/create",
+                                config.base_url
+                            ))
+                            .set("Authorization", &format!("Bearer {access_token}"))
+                    },
+                    json!({
+                        "category": "group",
+                        "type": lesson_type,
+                        "moduleId": config.module_id,
+                        "groupId": group_id,
+                        "name": lesson.name,
+                        "link": lesson.link
+                    }),
+                    cli.max_retries,
+                    cli.retry_base_delay_ms,
+                )?
                 .into_json()?;
 
                 if res.success {
@@ -243,28 +608,35 @@ fn main() -> Result<()> {
         }
         Commands::Remove { group_id } => {
             let refresh_token = get_refresh_token()?;
-            let access_token = get_access_token(&refresh_token)?;
-
-            let res: LessonListResponse = agent.get(&format!("https://api.admin.edu.goiteens.com/api/v1/training-module/additional-material/list?moduleId=17063573&groupId={group_id}"))
-                .set("Authorization", &format!("Bearer {access_token}"))
-                .call()?
-                .into_json()?;
-
-            if !res.success {
-                bail!("GoITeens LMS returned an error: {}", res.error);
-            }
+            let access_token = get_access_token(&refresh_token, &config)?;
 
-            let lessons = res
-                .group
-                .context("GoITeens LMS returned an invalid response")?;
+            let lessons = get_lessons(&agent, &access_token, group_id, &config)?;
 
             for lesson in lessons {
-                let res: GenericResponse = agent.post("https://api.admin.edu.goiteens.com/api/v1/training-module/additional-material/delete")
-                    .set("Authorization", &format!("Bearer {access_token}"))
-                    .send_json(json!({
+                if cli.dry_run {
+                    println!(
+                        "Would delete lesson \"{}\" (material id {})",
+                        lesson.name, lesson.id
+                    );
+                    continue;
+                }
+
+                let res: GenericResponse = send_json_with_retries(
+                    || {
+                        agent
+                            .post(&format!(
+                                "{}/api/v1/training-module/additional-material/delete",
+                                config.base_url
+                            ))
+                            .set("Authorization", &format!("Bearer {access_token}"))
+                    },
+                    json!({
                         "materialId": lesson.id
-                    }))?
-                    .into_json()?;
+                    }),
+                    cli.max_retries,
+                    cli.retry_base_delay_ms,
+                )?
+                .into_json()?;
                 if res.success {
                     if !cli.quiet {
                         println!("Successfully removed lesson {}", lesson.name);
@@ -278,16 +650,134 @@ fn main() -> Result<()> {
                 }
             }
         }
+        Commands::Sync {
+            group_id,
+            input,
+            format,
+        } => {
+            let refresh_token = get_refresh_token()?;
+            let access_token = get_access_token(&refresh_token, &config)?;
+
+            let format = detect_format(&input, format);
+            let desired = build_lessons(&input, format, group_id)?;
+
+            let mut desired_by_group: AHashMap<u64, Vec<Lesson>> = AHashMap::default();
+            for InputLesson { lesson, group_id } in desired {
+                desired_by_group.entry(group_id).or_default().push(lesson);
+            }
+
+            for (group_id, desired) in desired_by_group {
+                let existing = get_lessons(&agent, &access_token, group_id, &config)?;
+
+                let matches = |existing: &LessonResponse, lesson: &Lesson| {
+                    existing.name == lesson.name
+                        && existing.link == lesson.link
+                        && existing.lesson_type == material_type(&lesson.link)
+                };
+
+                let to_create = desired
+                    .iter()
+                    .filter(|lesson| !existing.iter().any(|existing| matches(existing, lesson)));
+
+                let to_delete = existing
+                    .iter()
+                    .filter(|existing| !desired.iter().any(|lesson| matches(existing, lesson)));
+
+                for lesson in to_create {
+                    let lesson_type = material_type(&lesson.link);
+
+                    if cli.dry_run {
+                        println!(
+                            "Would create {lesson_type} lesson \"{}\" -> {}",
+                            lesson.name, lesson.link
+                        );
+                        continue;
+                    }
+
+                    let res: GenericResponse = send_json_with_retries(
+                        || {
+                            agent
+                                .post(&format!(
+                                    "{}/api/v1/training-module/additional-material/create",
+                                    config.base_url
+                                ))
+                                .set("Authorization", &format!("Bearer {access_token}"))
+                        },
+                        json!({
+                            "category": "group",
+                            "type": lesson_type,
+                            "moduleId": config.module_id,
+                            "groupId": group_id,
+                            "name": lesson.name,
+                            "link": lesson.link
+                        }),
+                        cli.max_retries,
+                        cli.retry_base_delay_ms,
+                    )?
+                    .into_json()?;
+
+                    if res.success {
+                        if !cli.quiet {
+                            println!("Successfully uploaded lesson \"{}\"", lesson.name);
+                        }
+                    } else {
+                        bail!(
+                            "When uploading lesson \"{}\" GoITeens LMS returned an error: {}",
+                            lesson.name,
+                            res.error
+                        );
+                    }
+                }
+
+                for lesson in to_delete {
+                    if cli.dry_run {
+                        println!(
+                            "Would delete lesson \"{}\" (material id {})",
+                            lesson.name, lesson.id
+                        );
+                        continue;
+                    }
+
+                    let res: GenericResponse = send_json_with_retries(
+                        || {
+                            agent
+                                .post(&format!(
+                                    "{}/api/v1/training-module/additional-material/delete",
+                                    config.base_url
+                                ))
+                                .set("Authorization", &format!("Bearer {access_token}"))
+                        },
+                        json!({
+                            "materialId": lesson.id
+                        }),
+                        cli.max_retries,
+                        cli.retry_base_delay_ms,
+                    )?
+                    .into_json()?;
+                    if res.success {
+                        if !cli.quiet {
+                            println!("Successfully removed lesson {}", lesson.name);
+                        }
+                    } else {
+                        bail!(
+                            "When removing lesson \"{}\" GoITeens LMS returned an error: {}",
+                            lesson.name,
+                            res.error
+                        );
+                    }
+                }
+            }
+        }
     };
     Ok(())
 }
 
-fn log_in(username: &str, password: &str, quiet: bool) -> Result<()> {
+fn log_in(username: &str, password: &str, quiet: bool, config: &LmsConfig) -> Result<()> {
     if !quiet {
         println!("Logging in... It's going to take a long time");
     }
 
-    let res: TokenResponse = ureq::post("https://api.admin.edu.goiteens.com/api/v1/auth/login")
+    let res: TokenResponse = ureq::post(&format!("{}/api/v1/auth/login", config.base_url))
         .send_json(json!({
             "username": username,
             "password": password,
@@ -310,8 +800,48 @@ fn log_in(username: &str, password: &str, quiet: bool) -> Result<()> {
     Ok(())
 }
 
-fn get_access_token(refresh_token: &str) -> Result<String> {
-    let res: TokenResponse = ureq::post("https://api.admin.edu.goiteens.com/api/v1/auth/refresh")
+/// How long before its actual expiry a cached access token is considered stale and refreshed anyway.
+const ACCESS_TOKEN_EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// Reads the `exp` claim out of a JWT's payload segment, without verifying its signature (the
+/// server re-validates on every request, so this is only used to decide whether to bother asking
+/// for a new one).
+fn jwt_exp(token: &str) -> Result<u64> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .context("Malformed access token: missing payload segment")?;
+    let decoded = URL_SAFE_NO_PAD
+        .decode(payload)
+        .context("Malformed access token: payload is not valid base64url")?;
+    let claims: JwtClaims = serde_json::from_slice(&decoded)
+        .context("Malformed access token: payload is not valid JSON")?;
+    Ok(claims.exp)
+}
+
+/// Cached access token alongside the base URL it was issued for, so a token obtained from one
+/// LMS environment never gets replayed against a different `--base-url`/`LMS_BASE_URL`.
+#[derive(Serialize, Deserialize)]
+struct CachedAccessToken {
+    base_url: String,
+    access_token: String,
+}
+
+fn get_access_token(refresh_token: &str, config: &LmsConfig) -> Result<String> {
+    if let Ok(cached) = fs::read_to_string("./access-token.txt") {
+        if let Ok(cached) = serde_json::from_str::<CachedAccessToken>(&cached) {
+            if cached.base_url == config.base_url {
+                if let Ok(exp) = jwt_exp(&cached.access_token) {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                    if now + ACCESS_TOKEN_EXPIRY_MARGIN_SECS < exp {
+                        return Ok(cached.access_token);
+                    }
+                }
+            }
+        }
+    }
+
+    let res: TokenResponse = ureq::post(&format!("{}/api/v1/auth/refresh", config.base_url))
         .set("Cookie", &format!("refreshToken={refresh_token}"))
         .call()?
         .into_json()?;
@@ -323,6 +853,15 @@ fn get_access_token(refresh_token: &str) -> Result<String> {
     let mut file = File::create("refresh-token.txt")?;
     file.write_all(res.refresh_token.as_bytes())?;
 
+    let mut access_token_file = File::create("access-token.txt")?;
+    access_token_file.write_all(
+        serde_json::to_string(&CachedAccessToken {
+            base_url: config.base_url.clone(),
+            access_token: res.access_token.clone(),
+        })?
+        .as_bytes(),
+    )?;
+
     Ok(res.access_token)
 }
 